@@ -1,6 +1,13 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use serde_json::Value;
 use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use url::form_urlencoded;
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -8,9 +15,64 @@ type HmacSha256 = Hmac<Sha256>;
 pub enum ValidationError {
     InvalidInput,
     InvalidHash,
+    Expired,
+    /// Signing or encoding the session JWT failed.
+    #[cfg(feature = "jwt")]
+    TokenError,
+}
+
+/// Configuration for the time-based freshness checks performed by [`validate_with`].
+///
+/// `validate` uses [`Validation::default`], which disables the freshness check entirely, so
+/// existing callers keep their current behavior.
+#[derive(Debug, Clone)]
+pub struct Validation {
+    /// Maximum age a payload is accepted for, measured from its `auth_date`. `None` disables
+    /// the freshness check.
+    pub max_age: Option<Duration>,
+    /// Extra slack added on top of `max_age` to tolerate clock skew between Telegram and this
+    /// server.
+    pub leeway: Duration,
+    /// Whether the payload must contain an `auth_date` field at all. When `false`, a payload
+    /// missing `auth_date` simply skips the freshness check.
+    pub require_auth_date: bool,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            max_age: None,
+            leeway: Duration::from_secs(5),
+            require_auth_date: false,
+        }
+    }
 }
 
-fn extract_data_check_string(value: Value) -> Result<(String, String), ValidationError> {
+/// Builds the data-check-string Telegram expects the HMAC to be computed over: every field
+/// except `hash`, sorted lexicographically by key and joined as `key=value` with `\n`. Shared by
+/// both the Login Widget (`validate`/`validate_with`) and Mini App (`validate_webapp`) schemes,
+/// which only differ in how the HMAC key is derived.
+fn data_check_string_from_map(
+    kv: BTreeMap<String, String>,
+) -> Result<(String, String, BTreeMap<String, String>), ValidationError> {
+    let hash = kv
+        .get("hash")
+        .ok_or(ValidationError::InvalidInput)?
+        .to_string();
+
+    let data_check_string = kv
+        .iter()
+        .filter(|(key, _)| key != &"hash")
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok((hash, data_check_string, kv))
+}
+
+fn extract_data_check_string(
+    value: Value,
+) -> Result<(String, String, BTreeMap<String, String>), ValidationError> {
     match value {
         Value::Object(object) => {
             let kv = object
@@ -23,28 +85,66 @@ fn extract_data_check_string(value: Value) -> Result<(String, String), Validatio
                     })
                     .map(|value| (key, value))
                 })
-                .collect::<std::collections::BTreeMap<String, String>>();
-
-            let hash = kv
-                .get("hash")
-                .ok_or(ValidationError::InvalidInput)?
-                .to_string();
-
-            Ok((
-                hash,
-                kv.iter()
-                    .filter(|(key, _)| key != &"hash")
-                    .map(|(key, value)| format!("{}={}", key, value))
-                    .collect::<Vec<_>>()
-                    .join("\n"),
-            ))
+                .collect::<BTreeMap<String, String>>();
+
+            data_check_string_from_map(kv)
         }
         _ => Err(ValidationError::InvalidInput),
     }
 }
 
-pub fn validate(input: &str, bot_token: &str) -> Result<(), ValidationError> {
-    let (check_hash, data_check_string) = serde_json::from_str(input)
+/// Compares a computed MAC against the `hash` hex string from the payload in constant time, so
+/// verification time does not leak how many leading bytes matched.
+fn hash_matches(computed: &[u8], expected_hex: &str) -> Result<bool, ValidationError> {
+    let expected = hex::decode(expected_hex).map_err(|_| ValidationError::InvalidInput)?;
+
+    if expected.len() != computed.len() {
+        return Ok(false);
+    }
+
+    Ok(computed.ct_eq(&expected).into())
+}
+
+fn check_auth_date(
+    kv: &BTreeMap<String, String>,
+    validation: &Validation,
+) -> Result<(), ValidationError> {
+    let auth_date = match kv.get("auth_date") {
+        Some(auth_date) => Some(
+            auth_date
+                .parse::<u64>()
+                .map_err(|_| ValidationError::InvalidInput)?,
+        ),
+        None if validation.require_auth_date => return Err(ValidationError::InvalidInput),
+        None => None,
+    };
+
+    let (Some(auth_date), Some(max_age)) = (auth_date, validation.max_age) else {
+        return Ok(());
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| ValidationError::InvalidInput)?
+        .as_secs();
+
+    if now.saturating_sub(auth_date) > (max_age + validation.leeway).as_secs() {
+        return Err(ValidationError::Expired);
+    }
+
+    Ok(())
+}
+
+/// Validates a Telegram Login Widget payload, additionally enforcing the freshness rules in
+/// `validation`.
+///
+/// See [`validate`] for the plain HMAC-only check.
+pub fn validate_with(
+    input: &str,
+    bot_token: &str,
+    validation: &Validation,
+) -> Result<(), ValidationError> {
+    let (check_hash, data_check_string, kv) = serde_json::from_str(input)
         .map_err(|_| ValidationError::InvalidInput)
         .map(extract_data_check_string)??;
 
@@ -54,15 +154,122 @@ pub fn validate(input: &str, bot_token: &str) -> Result<(), ValidationError> {
         HmacSha256::new_from_slice(&bot_token_hash).map_err(|_| ValidationError::InvalidInput)?;
     mac.update(data_check_string.as_bytes());
 
-    let result = hex::encode(mac.finalize().into_bytes());
+    if !hash_matches(&mac.finalize().into_bytes(), &check_hash)? {
+        return Err(ValidationError::InvalidHash);
+    }
+
+    check_auth_date(&kv, validation)
+}
 
-    if result == check_hash {
+/// Validates a Telegram Login Widget payload against the bot token's HMAC, without any
+/// freshness check. Equivalent to `validate_with(input, bot_token, &Validation::default())`.
+pub fn validate(input: &str, bot_token: &str) -> Result<(), ValidationError> {
+    validate_with(input, bot_token, &Validation::default())
+}
+
+/// The fields Telegram includes in a Login Widget payload, ready to use as the `T` in
+/// [`validate_into`] when callers don't need a custom type.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TelegramUser {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub photo_url: Option<String>,
+    pub auth_date: i64,
+}
+
+/// Validates a Telegram Login Widget payload like [`validate`], then deserializes it (minus the
+/// `hash` field) into `T`, sparing callers from re-parsing the same JSON to read the user's id,
+/// name, username, etc. Use [`TelegramUser`] as `T` unless a custom shape is needed.
+pub fn validate_into<T: DeserializeOwned>(
+    input: &str,
+    bot_token: &str,
+) -> Result<T, ValidationError> {
+    validate(input, bot_token)?;
+
+    let mut value: Value =
+        serde_json::from_str(input).map_err(|_| ValidationError::InvalidInput)?;
+
+    if let Value::Object(ref mut map) = value {
+        map.remove("hash");
+    }
+
+    serde_json::from_value(value).map_err(|_| ValidationError::InvalidInput)
+}
+
+/// Validates a Telegram Mini App `initData` string.
+///
+/// Unlike the Login Widget, `initData` arrives as a URL-encoded query string and derives its
+/// HMAC key as `HMAC_SHA256(key = "WebAppData", msg = bot_token)` rather than `SHA256(bot_token)`.
+/// See <https://core.telegram.org/bots/webapps#validating-data-received-via-the-mini-app>.
+pub fn validate_webapp(init_data: &str, bot_token: &str) -> Result<(), ValidationError> {
+    let kv = form_urlencoded::parse(init_data.as_bytes())
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<BTreeMap<String, String>>();
+
+    let (check_hash, data_check_string, _) = data_check_string_from_map(kv)?;
+
+    let mut secret_key_mac =
+        HmacSha256::new_from_slice(b"WebAppData").map_err(|_| ValidationError::InvalidInput)?;
+    secret_key_mac.update(bot_token.as_bytes());
+    let secret_key = secret_key_mac.finalize().into_bytes();
+
+    let mut mac =
+        HmacSha256::new_from_slice(&secret_key).map_err(|_| ValidationError::InvalidInput)?;
+    mac.update(data_check_string.as_bytes());
+
+    if hash_matches(&mac.finalize().into_bytes(), &check_hash)? {
         Ok(())
     } else {
         Err(ValidationError::InvalidHash)
     }
 }
 
+/// Session claims minted by [`issue_session_jwt`] after a Telegram login has been verified.
+/// Downstream services can decode and trust these without re-contacting Telegram.
+#[cfg(feature = "jwt")]
+#[derive(Debug, Clone, serde::Serialize, Deserialize)]
+pub struct Claims {
+    /// The Telegram user id, as a string (the `sub` claim is conventionally a string).
+    pub sub: String,
+    pub username: Option<String>,
+    pub iat: u64,
+    pub exp: u64,
+}
+
+/// Verifies a Telegram Login Widget payload like [`validate`], then mints an HS256 session JWT
+/// for it, so a backend can turn a one-shot Telegram login into a stateless session without
+/// re-contacting Telegram on every request.
+#[cfg(feature = "jwt")]
+pub fn issue_session_jwt(
+    input: &str,
+    bot_token: &str,
+    signing_secret: &[u8],
+    ttl: Duration,
+) -> Result<String, ValidationError> {
+    let user: TelegramUser = validate_into(input, bot_token)?;
+
+    let iat = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| ValidationError::InvalidInput)?
+        .as_secs();
+
+    let claims = Claims {
+        sub: user.id.to_string(),
+        username: user.username,
+        iat,
+        exp: iat + ttl.as_secs(),
+    };
+
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(signing_secret),
+    )
+    .map_err(|_| ValidationError::TokenError)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,6 +296,14 @@ mod tests {
             "hash":"605c4ad6d7d25df74071df9b8956dea769c5b65fa0ba09c22bf28caf1bc7d4bb"
         }"#;
 
+    const NON_HEX_HASH_DATA: &'static str = r#"
+        {
+            "id": 12345678,
+            "first_name":"Name",
+            "auth_date":1732679640,
+            "hash":"not-hex"
+        }"#;
+
     #[test]
     fn valid_data_valid_bot_token() {
         assert_eq!(validate(VALID_DATA, VALID_BOT_TOKEN), Ok(()));
@@ -117,4 +332,111 @@ mod tests {
             Err(ValidationError::InvalidInput)
         );
     }
+
+    #[test]
+    fn expired_auth_date_is_rejected() {
+        let validation = Validation {
+            max_age: Some(Duration::from_secs(60)),
+            leeway: Duration::from_secs(0),
+            require_auth_date: false,
+        };
+        assert_eq!(
+            validate_with(VALID_DATA, VALID_BOT_TOKEN, &validation),
+            Err(ValidationError::Expired)
+        );
+    }
+
+    #[test]
+    fn required_auth_date_present_still_passes() {
+        let validation = Validation {
+            max_age: None,
+            leeway: Duration::from_secs(5),
+            require_auth_date: true,
+        };
+        assert_eq!(
+            validate_with(VALID_DATA, VALID_BOT_TOKEN, &validation),
+            Ok(())
+        );
+    }
+
+    const VALID_INIT_DATA: &'static str = "auth_date=1732679640&query_id=AAHdF6IQAAAAAN0XohDhrOrc&user=%7B%22id%22%3A12345678%2C%22first_name%22%3A%22Name%22%2C%22username%22%3A%22username%22%7D&hash=bac846441a565b7f78e223d8bd2afd973974049266949f2451b6c3a86a98635f";
+
+    #[test]
+    fn valid_init_data_valid_bot_token() {
+        assert_eq!(validate_webapp(VALID_INIT_DATA, VALID_BOT_TOKEN), Ok(()));
+    }
+
+    #[test]
+    fn valid_init_data_invalid_bot_token() {
+        assert_eq!(
+            validate_webapp(VALID_INIT_DATA, INVALID_BOT_TOKEN),
+            Err(ValidationError::InvalidHash)
+        );
+    }
+
+    #[test]
+    fn init_data_arbitrary_data() {
+        assert_eq!(
+            validate_webapp("blabla", VALID_BOT_TOKEN),
+            Err(ValidationError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn non_hex_hash_is_invalid_input() {
+        assert_eq!(
+            validate(NON_HEX_HASH_DATA, VALID_BOT_TOKEN),
+            Err(ValidationError::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn validate_into_returns_telegram_user() {
+        let user: TelegramUser = validate_into(VALID_DATA, VALID_BOT_TOKEN).unwrap();
+        assert_eq!(user.id, 12345678);
+        assert_eq!(user.first_name, "Name");
+        assert_eq!(user.username.as_deref(), Some("username"));
+        assert_eq!(user.auth_date, 1732679640);
+    }
+
+    #[test]
+    fn validate_into_invalid_bot_token() {
+        assert_eq!(
+            validate_into::<TelegramUser>(VALID_DATA, INVALID_BOT_TOKEN),
+            Err(ValidationError::InvalidHash)
+        );
+    }
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn issue_session_jwt_roundtrips_claims() {
+        let token =
+            issue_session_jwt(VALID_DATA, VALID_BOT_TOKEN, b"signing-secret", Duration::from_secs(3600))
+                .unwrap();
+
+        let decoded = jsonwebtoken::decode::<Claims>(
+            &token,
+            &jsonwebtoken::DecodingKey::from_secret(b"signing-secret"),
+            &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256),
+        )
+        .unwrap();
+
+        assert_eq!(decoded.claims.sub, "12345678");
+        assert_eq!(decoded.claims.username.as_deref(), Some("username"));
+        assert_eq!(decoded.claims.exp - decoded.claims.iat, 3600);
+    }
+
+    #[cfg(feature = "jwt")]
+    #[test]
+    fn issue_session_jwt_invalid_bot_token() {
+        assert_eq!(
+            issue_session_jwt(
+                VALID_DATA,
+                INVALID_BOT_TOKEN,
+                b"signing-secret",
+                Duration::from_secs(3600)
+            ),
+            Err(ValidationError::InvalidHash)
+        );
+    }
 }